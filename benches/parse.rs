@@ -0,0 +1,68 @@
+//! Timings for very large command lines, such as those built by `xargs`.
+//!
+//! Run with `cargo bench`.
+
+use bind_args::{parse, parse_streaming};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ARGUMENT_COUNT: usize = 50_000;
+const ITERATIONS: u32 = 20;
+
+fn main() {
+    let argv: Vec<String> = std::iter::once(String::from("program"))
+        .chain((0..ARGUMENT_COUNT).map(|i| match i % 4 {
+            0 => format!("--define=key{i}=value"),
+            1 => String::from("-v"),
+            2 => String::from("--include"),
+            _ => format!("file{i}.txt"),
+        }))
+        .collect();
+
+    bench("parse", || parse(argv.clone()).unwrap());
+
+    bench("parse streaming", || {
+        parse_streaming(argv.clone())
+            .unwrap()
+            .filter_map(Result::ok)
+            .count()
+    });
+
+    bench("parse and drain operands", || {
+        let mut bag = parse(argv.clone()).unwrap();
+        while let Some(operand) = bag.remove_operand() {
+            black_box(operand);
+        }
+        bag
+    });
+
+    bench("parse and drain options", || {
+        let mut bag = parse(argv.clone()).unwrap();
+        while let Some(value) = bag.remove_option("include") {
+            black_box(value);
+        }
+        bag
+    });
+
+    bench("parse and remove remaining", || {
+        let mut bag = parse(argv.clone()).unwrap();
+        black_box(bag.remove_remaining());
+        bag.is_empty()
+    });
+}
+
+fn bench<T>(name: &str, mut f: impl FnMut() -> T) {
+    black_box(f());
+
+    let mut total = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        black_box(f());
+        total += start.elapsed();
+    }
+
+    println!(
+        "{name:<30} {:>10.3?} per iteration ({ARGUMENT_COUNT} arguments)",
+        total / ITERATIONS
+    );
+}
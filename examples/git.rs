@@ -18,11 +18,6 @@ More info
 
 "#;
 
-#[derive(Default)]
-struct Root {
-    verbose: bool,
-}
-
 #[derive(Default)]
 struct Remote {
     level: String,
@@ -53,12 +48,12 @@ fn handle_remote(mut args: ArgumentBag) -> anyhow::Result<()> {
 }
 
 fn handle_root(mut args: ArgumentBag) -> anyhow::Result<()> {
-    let mut root = Root::default();
     if args.remove_flag("help") || args.remove_flag("h") {
         println!("{ROOT_HELP}");
         std::process::exit(0);
     }
-    root.verbose = args.remove_flag("verbose");
+    // Accepted, but the root command has nothing to be verbose about
+    args.remove_flag("verbose");
 
     if !args.is_empty() {
         let remaining = args.remove_remaining().join(",");
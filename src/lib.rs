@@ -5,28 +5,80 @@
 //! operands may be extracted.
 //!
 //! You get an instance of the bag by callind [`parse`] or [`parse_env`].
+//! [`parse_relaxed`] is a variant that never fails, for fuzzing and hostile input.
+//! [`parse_streaming`] parses arguments lazily without building a bag.
+//!
+//! # Features
+//!
+//! - `glob`: adds [`ParseOptions::expand_globs`], for platforms whose shells leave wildcards in
+//!   operands as-is.
+//! - `miette`: implements `miette::Diagnostic` for [`ParseError`], and adds
+//!   [`ParseError::with_command_line`] to show errors in context.
+//! - `tracing`: emits `tracing` events describing how each argument was classified. Events
+//!   include switch names and lengths, but never values or operands, which may be secrets.
+//! - `unicode-normalization`: adds [`ParseOptions::normalize_unicode`].
 
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::Display;
+use std::io::{IsTerminal, Read};
+use std::ops::Range;
+use std::sync::Arc;
+
+// Forwards to `tracing::trace!` when the `tracing` feature is enabled, and expands to nothing
+// otherwise.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+// Switches are interned in the bag by their spelling (e.g. `--blah`), so a switch given many times
+// (e.g. repeated `-D` defines) is stored once and compared by pointer.
+// The spelling keeps the dashes the switch was given with: folding can shorten a long name to a
+// single character, and it must not turn into a short one.
 
 // e.g.: --blah
 #[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
 struct Switch {
-    name: String,
+    spelling: Arc<str>,
+    // Position in the input, where `0` is the program name
+    index: usize,
 }
 
 // e.g.: --blah=hello
 #[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
 struct SwitchWithValue {
-    name: String,
-    value: String,
+    spelling: Arc<str>,
+    value: Box<str>,
+    // Position in the input, where `0` is the program name
+    index: usize,
+}
+
+// Short names are exactly one character and take a single dash
+fn dashes(name: &str) -> &'static str {
+    if name.len() == 1 {
+        "-"
+    } else {
+        "--"
+    }
+}
+
+// Long names are at least two characters and short names exactly one
+fn is_valid_name(name: &str, dashes: usize) -> bool {
+    match dashes {
+        1 => name.len() == 1,
+        _ => name.len() >= 2,
+    }
 }
 
 // e.g.: hello
 #[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
 struct Operand {
     position: usize,
-    value: String,
+    token: Box<str>,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -37,62 +89,214 @@ enum Arg {
     #[default]
     Empty,
 }
-impl Display for Arg {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+
+impl Arg {
+    // Returns the value held by an operand or an option
+    fn into_value(self) -> Option<String> {
         match self {
-            Self::Empty => Ok(()),
-            Self::Switch(flag) => {
-                if flag.name.len() == 1 {
-                    write!(f, "-{}", flag.name)
-                } else {
-                    write!(f, "--{}", flag.name)
-                }
+            Self::Operand(op) => Some(op.token.into_string()),
+            Self::SwitchWithValue(opt) => Some(opt.value.into_string()),
+            Self::Switch(_) | Self::Empty => None,
+        }
+    }
+
+    // Returns the argument as it was given on the command line
+    fn into_token(self) -> Option<String> {
+        match self {
+            Self::Switch(flag) => Some(flag.spelling.to_string()),
+            Self::SwitchWithValue(opt) => Some(format!("{}={}", opt.spelling, opt.value)),
+            Self::Operand(op) => Some(op.token.into_string()),
+            Self::Empty => None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        let Self::Empty = self else { return false };
+        true
+    }
+
+    // Returns `true` when both would be given the same way on the command line, wherever they were
+    // given
+    fn is_same_argument(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Switch(a), Self::Switch(b)) => a.spelling == b.spelling,
+            (Self::SwitchWithValue(a), Self::SwitchWithValue(b)) => {
+                a.spelling == b.spelling && a.value == b.value
             }
-            Self::SwitchWithValue(opt) => {
-                if opt.name.len() == 1 {
-                    write!(f, "-{}={}", opt.name, opt.value)
-                } else {
-                    write!(f, "--{}={}", opt.name, opt.value)
-                }
+            (Self::Operand(a), Self::Operand(b)) => a.token == b.token,
+            (Self::Empty, Self::Empty) => true,
+            _ => false,
+        }
+    }
+
+    // Returns `true` for a flag or an option with the interned `spelling`
+    fn is_spelled(&self, spelling: &Arc<str>) -> bool {
+        match self {
+            Self::Switch(Switch { spelling: s, .. })
+            | Self::SwitchWithValue(SwitchWithValue { spelling: s, .. }) => {
+                Arc::ptr_eq(s, spelling)
             }
-            Self::Operand(op) => write!(f, "{}", op.value),
+            Self::Operand(_) | Self::Empty => false,
         }
     }
 }
 
-impl Arg {
-    fn into_operand(self) -> Operand {
-        let Self::Operand(o) = self else {
-            panic!("expected Arg::Operand variant");
-        };
-        o
-    }
+// Where a switch occurs in the bag
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Occurrences {
+    // The shared copy of the spelling
+    spelling: Arc<str>,
+    // Slots in `args` outside of this range do not hold this switch
+    slots: Range<usize>,
+}
+
+// How switch names are rewritten before they are stored or looked up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Folding {
+    ascii_case: bool,
+    #[cfg(feature = "unicode-normalization")]
+    nfc: bool,
+}
 
-    fn into_switch_with_value(self) -> SwitchWithValue {
-        let Self::SwitchWithValue(o) = self else {
-            panic!("expected Arg::Option variant");
+impl Folding {
+    fn apply<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        #[cfg(feature = "unicode-normalization")]
+        let name: Cow<str> = if self.nfc && !unicode_normalization::is_nfc(name) {
+            unicode_normalization::UnicodeNormalization::nfc(name)
+                .collect::<String>()
+                .into()
+        } else {
+            name.into()
         };
-        o
-    }
+        #[cfg(not(feature = "unicode-normalization"))]
+        let name = Cow::Borrowed(name);
 
-    fn is_empty(&self) -> bool {
-        let Self::Empty = self else { return false };
-        true
+        if self.ascii_case && name.bytes().any(|b| b.is_ascii_uppercase()) {
+            name.to_ascii_lowercase().into()
+        } else {
+            name
+        }
     }
 }
 
+/// Which occurrence is removed first when a flag or an option is given more than once
+///
+/// See [`ArgumentBag::set_precedence`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Precedence {
+    /// The first occurrence on the command line wins
+    #[default]
+    FirstWins,
+    /// The last occurrence on the command line wins
+    LastWins,
+}
+
 /// A bag of parsed command line arguments
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+///
+/// Two bags are equal when they have the same program name and hold the same arguments, in the
+/// same order.
+#[derive(Debug, Default, Clone)]
 pub struct ArgumentBag {
     /// The name of the program being run
     pub program_name: String,
     args: Vec<Arg>,
     ignored: Vec<String>,
+    // Index of the first slot in `args` that may not be empty
+    start: usize,
+    // Number of slots in `args` that are not empty
+    live: usize,
+    // Index of the first slot in `args` that may hold an operand
+    operands_start: usize,
+    // Every switch seen while parsing, keyed by its spelling and mapped to where it may still occur
+    switches: HashMap<Arc<str>, Occurrences>,
+    // Reused to spell out the switch being looked up
+    spelling: String,
+    precedence: Precedence,
+    folding: Folding,
+}
+
+impl PartialEq for ArgumentBag {
+    fn eq(&self, other: &Self) -> bool {
+        // The other fields only speed up lookups or configure them
+        self.program_name == other.program_name
+            && self.ignored == other.ignored
+            && self.live == other.live
+            && self
+                .live_args()
+                .zip(other.live_args())
+                .all(|(a, b)| a.is_same_argument(b))
+    }
 }
 
+impl Eq for ArgumentBag {}
+
 impl ArgumentBag {
+    // Returns the arguments that have not been removed yet
+    fn live_args(&self) -> impl Iterator<Item = &Arg> {
+        self.args.iter().filter(|arg| !arg.is_empty())
+    }
+
+    // Empties the slot at index `i`, keeping `start` and `live` up to date
+    fn take(&mut self, i: usize) -> Arg {
+        let arg = std::mem::take(&mut self.args[i]);
+        if !arg.is_empty() {
+            self.live -= 1;
+        }
+
+        while self.args.get(self.start).is_some_and(Arg::is_empty) {
+            self.start += 1;
+        }
+        arg
+    }
+
+    // Looks up the interned spelling of the switch called `name` and the slots that may hold it.
+    //
+    // The range is narrowed first, so that when it is not empty both of its ends hold that switch.
+    fn lookup(&mut self, name: &str) -> Option<(Arc<str>, Range<usize>)> {
+        // Like when parsing, the dashes are decided before the name is folded
+        self.spelling.clear();
+        self.spelling.push_str(dashes(name));
+        self.spelling.push_str(name);
+        let occurrences = self
+            .switches
+            .get_mut(&*self.folding.apply(&self.spelling))?;
+        let interned = &occurrences.spelling;
+
+        let mut start = occurrences.slots.start.max(self.start);
+        let mut end = occurrences.slots.end.min(self.args.len());
+        while start < end && !self.args[start].is_spelled(interned) {
+            start += 1;
+        }
+        while end > start && !self.args[end - 1].is_spelled(interned) {
+            end -= 1;
+        }
+
+        occurrences.slots = start..end;
+        Some((Arc::clone(interned), start..end))
+    }
+
+    /// Sets which occurrence of a repeated flag or option is removed first.
+    ///
+    /// The default is [`Precedence::FirstWins`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bind_args::{parse, Precedence};
+    ///
+    /// let mut bag = parse(["program", "--log-level=info", "--log-level=debug"]).unwrap();
+    /// bag.set_precedence(Precedence::LastWins);
+    /// assert_eq!(bag.remove_option("log-level").as_deref(), Some("debug"));
+    /// assert_eq!(bag.remove_option("log-level").as_deref(), Some("info"));
+    /// ```
+    pub fn set_precedence(&mut self, precedence: Precedence) {
+        self.precedence = precedence;
+    }
+
     /// Removes the first flag with the given name from the bag if it exists.
     ///
+    /// The last one is removed instead under [`Precedence::LastWins`].
+    ///
     /// # Example
     ///
     /// ```
@@ -103,23 +307,26 @@ impl ArgumentBag {
     /// assert_eq!(bag.remove_flag("flag2"), false);
     /// ```
     pub fn remove_flag(&mut self, name: &str) -> bool {
-        for i in 0..self.args.len() {
-            let Arg::Switch(flag) = &self.args[i] else {
-                continue;
-            };
+        let Some((interned, mut slots)) = self.lookup(name) else {
+            return false;
+        };
 
-            if flag.name != name {
-                continue;
-            }
+        let is_flag =
+            |arg: &Arg| matches!(arg, Arg::Switch(flag) if Arc::ptr_eq(&flag.spelling, &interned));
+        let found = match self.precedence {
+            Precedence::FirstWins => slots.find(|&i| is_flag(&self.args[i])),
+            Precedence::LastWins => slots.rfind(|&i| is_flag(&self.args[i])),
+        };
 
-            std::mem::take(&mut self.args[i]);
-            return true;
-        }
-        false
+        let Some(i) = found else { return false };
+        self.take(i);
+        true
     }
 
     /// Removes the first option with the given `name` and returns its value.
     ///
+    /// The last one is removed instead under [`Precedence::LastWins`].
+    ///
     /// This works with both space-separated and `=`-separated option forms (i.e. `--option=value`
     /// and `--option value`)
     ///
@@ -165,34 +372,147 @@ impl ArgumentBag {
     /// assert!(bag.is_empty());
     /// ```
     pub fn remove_option(&mut self, name: &str) -> Option<String> {
-        for i in 0..self.args.len() {
-            match &self.args[i] {
-                Arg::SwitchWithValue(s) => {
-                    if s.name != name {
-                        continue;
-                    }
+        let (_, slots) = self.lookup(name)?;
+        if slots.is_empty() {
+            return None;
+        }
 
-                    let arg = std::mem::take(&mut self.args[i]);
-                    return Some(arg.into_switch_with_value().value);
-                }
-                Arg::Switch(s) => {
-                    if s.name != name {
-                        continue;
-                    }
+        let i = match self.precedence {
+            Precedence::FirstWins => slots.start,
+            Precedence::LastWins => slots.end - 1,
+        };
+
+        match &self.args[i] {
+            Arg::SwitchWithValue(_) => self.take(i).into_value(),
+            Arg::Switch(_) => {
+                let Some(Arg::Operand(_)) = self.args.get(i + 1) else {
+                    trace!(
+                        name,
+                        "switch is not followed by a value, leaving it as a flag"
+                    );
+                    return None;
+                };
+
+                self.take(i);
+                self.take(i + 1).into_value()
+            }
+            Arg::Operand(_) | Arg::Empty => None,
+        }
+    }
+
+    /// Removes every option with the given `name` and returns their values, each with the position
+    /// of its switch in the input.
+    ///
+    /// Values are returned in the order they were given, whatever the [`Precedence`].
+    /// Positions count every argument passed to [`parse`], where `0` is the program name, so they
+    /// can be used to report errors such as "argument 3 is invalid".
+    /// Switches with this name that have no value are left in the bag as flags.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bind_args::parse;
+    ///
+    /// let mut bag = parse(["program", "-I", "src", "-v", "-I=include"]).unwrap();
+    /// assert_eq!(
+    ///     bag.remove_option_occurrences("I"),
+    ///     vec![(1, String::from("src")), (4, String::from("include"))]
+    /// );
+    /// assert!(bag.remove_option_occurrences("I").is_empty());
+    /// ```
+    pub fn remove_option_occurrences(&mut self, name: &str) -> Vec<(usize, String)> {
+        let Some((interned, slots)) = self.lookup(name) else {
+            return Vec::new();
+        };
 
+        let mut occurrences = Vec::new();
+        for i in slots {
+            match &self.args[i] {
+                Arg::SwitchWithValue(opt) if Arc::ptr_eq(&opt.spelling, &interned) => {
+                    let index = opt.index;
+                    occurrences.extend(self.take(i).into_value().map(|value| (index, value)));
+                }
+                Arg::Switch(flag) if Arc::ptr_eq(&flag.spelling, &interned) => {
                     let Some(Arg::Operand(_)) = self.args.get(i + 1) else {
-                        return None;
+                        continue;
                     };
 
-                    std::mem::take(&mut self.args[i]);
-                    let value = std::mem::take(&mut self.args[i + 1]);
-
-                    return Some(value.into_operand().value);
+                    let index = flag.index;
+                    self.take(i);
+                    occurrences.extend(self.take(i + 1).into_value().map(|value| (index, value)));
                 }
                 _ => continue,
-            };
+            }
         }
-        None
+        occurrences
+    }
+
+    /// Removes an option like [`remove_option`](crate::ArgumentBag::remove_option), reading its
+    /// value from a file when it starts with `@`.
+    ///
+    /// `--key=@secret.pem` yields the contents of `secret.pem`, unchanged (including any trailing
+    /// newline).
+    /// Values without a leading `@` are returned as-is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the option and the path when the file cannot be read, or is not
+    /// valid UTF-8.
+    /// The option is removed from the bag either way.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bind_args::parse;
+    ///
+    /// let mut bag = parse(["program", "--query=@does-not-exist.sql", "--name=plain"]).unwrap();
+    /// assert!(bag.remove_option_dereferenced("query").is_err());
+    /// assert_eq!(bag.remove_option_dereferenced("name").unwrap().as_deref(), Some("plain"));
+    /// ```
+    pub fn remove_option_dereferenced(&mut self, name: &str) -> std::io::Result<Option<String>> {
+        let Some(value) = self.remove_option(name) else {
+            return Ok(None);
+        };
+        let Some(path) = value.strip_prefix('@') else {
+            return Ok(Some(value));
+        };
+
+        std::fs::read_to_string(path).map(Some).map_err(|e| {
+            std::io::Error::new(e.kind(), format!("{}{name}: {path}: {e}", dashes(name)))
+        })
+    }
+
+    /// Removes an option like [`remove_option`](crate::ArgumentBag::remove_option), reading its
+    /// value from standard input when it is `-`.
+    ///
+    /// The value must be given as `--opt=-`, since a lone `-` is not a valid argument.
+    /// Standard input is read to the end and returned unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the option when standard input cannot be read, is not valid UTF-8,
+    /// or is a terminal (where the program would otherwise wait silently for the user to type).
+    /// The option is removed from the bag either way.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bind_args::parse;
+    ///
+    /// let mut bag = parse(["program", "--arg=inline"]).unwrap();
+    /// assert_eq!(bag.remove_option_or_stdin("arg").unwrap().as_deref(), Some("inline"));
+    /// ```
+    pub fn remove_option_or_stdin(&mut self, name: &str) -> std::io::Result<Option<String>> {
+        let Some(value) = self.remove_option(name) else {
+            return Ok(None);
+        };
+        if value != "-" {
+            return Ok(Some(value));
+        }
+
+        let stdin = std::io::stdin();
+        let is_terminal = stdin.is_terminal();
+        read_option_value(name, stdin.lock(), is_terminal).map(Some)
     }
 
     /// Removes the next operand from the argument bag, if any.
@@ -212,14 +532,15 @@ impl ArgumentBag {
     /// assert_eq!(bag.remove_operand().as_deref(), None);
     /// ```
     pub fn remove_operand(&mut self) -> Option<String> {
-        for i in 0..self.args.len() {
+        for i in self.start.max(self.operands_start)..self.args.len() {
             let Arg::Operand(_) = &self.args[i] else {
                 continue;
             };
 
-            let arg = std::mem::take(&mut self.args[i]);
-            return Some(arg.into_operand().value);
+            self.operands_start = i + 1;
+            return self.take(i).into_value();
         }
+        self.operands_start = self.args.len();
         None
     }
 
@@ -240,14 +561,13 @@ impl ArgumentBag {
     /// assert_eq!(bag.remove_remaining(), vec![String::from("arg")]);
     /// ```
     pub fn remove_remaining(&mut self) -> Vec<String> {
-        let mut leftover = vec![];
+        let mut leftover = Vec::with_capacity(self.live);
 
-        for i in 0..self.args.len() {
-            let current = &self.args[i];
-            if !current.is_empty() {
-                leftover.push(std::mem::take(&mut self.args[i]).to_string());
-            }
-        }
+        leftover.extend(self.args.drain(..).filter_map(Arg::into_token));
+
+        self.start = 0;
+        self.live = 0;
+        self.operands_start = 0;
         leftover
     }
 
@@ -278,13 +598,40 @@ impl ArgumentBag {
     /// assert!(bag.is_empty());
     /// ```
     pub fn is_empty(&self) -> bool {
-        self.args.iter().all(Arg::is_empty)
+        self.live == 0
+    }
+}
+
+// Reads the value of the option `name` from `input`, refusing to wait on a terminal
+fn read_option_value(
+    name: &str,
+    mut input: impl Read,
+    is_terminal: bool,
+) -> std::io::Result<String> {
+    if is_terminal {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "{}{name}: expected the value on stdin, but stdin is a terminal",
+                dashes(name)
+            ),
+        ));
     }
+
+    let mut value = String::new();
+    input.read_to_string(&mut value).map_err(|e| {
+        std::io::Error::new(e.kind(), format!("{}{name}: stdin: {e}", dashes(name)))
+    })?;
+    Ok(value)
 }
 
 /// Parses command line arguments from `std::env::args()`
 ///
 /// See [`parse`]
+///
+/// # Panics
+///
+/// Panics if any argument to the process is not valid Unicode.
 pub fn parse_env() -> Result<ArgumentBag, ParseError> {
     parse(std::env::args())
 }
@@ -309,133 +656,1089 @@ pub fn parse_env() -> Result<ArgumentBag, ParseError> {
 /// assert_eq!(bag.is_empty(), true);
 /// ```
 ///
-/// # Panics
+/// # Errors
 ///
-/// Panics if any argument to the process is not valid Unicode.
+/// Returns [`ParseError::MissingProgramName`] when the input is empty.
+/// Parsing never panics, so it is safe to use on untrusted input.
+///
+/// To also bound the size of the input, see [`ParseOptions`].
 pub fn parse<I, T>(arguments: I) -> Result<ArgumentBag, ParseError>
 where
     I: IntoIterator<Item = T>,
     T: Into<String>,
 {
-    let mut args = arguments
-        .into_iter()
-        .map(|i| i.into())
-        .filter(|s| !s.is_empty())
-        .peekable();
+    ParseOptions::new().parse(arguments)
+}
 
-    let program_name = args.next().expect("missing program name");
+/// Options for parsing command line arguments
+///
+/// The defaults match [`parse`].
+///
+/// # Limits
+///
+/// Services that accept command lines over the network can bound the input they are willing to
+/// process.
+/// Parsing stops reading input as soon as a limit is exceeded and returns
+/// [`ParseError::LimitExceeded`].
+/// Every limit counts the program name and empty arguments (which are otherwise dropped), and none
+/// is set by default.
+///
+/// ```
+/// use bind_args::{Limit, ParseError, ParseOptions};
+///
+/// let options = ParseOptions::new().max_arguments(2).max_argument_length(10);
+///
+/// assert!(options.parse(["program", "--verbose"]).is_ok());
+/// assert_eq!(
+///     options.parse(["program", "a", "b"]),
+///     Err(ParseError::LimitExceeded(Limit::Arguments(2)))
+/// );
+/// assert_eq!(
+///     options.parse(["program", "--far-too-long"]),
+///     Err(ParseError::LimitExceeded(Limit::ArgumentLength(10)))
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    max_arguments: Option<usize>,
+    max_argument_length: Option<usize>,
+    max_total_bytes: Option<usize>,
+    #[cfg(feature = "glob")]
+    expand_globs: bool,
+    folding: Folding,
+}
 
-    let mut parsed = Vec::new();
-    let mut ignored = Vec::new();
+impl ParseOptions {
+    /// Creates options that behave like [`parse`]
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-    let mut operand_count = 0;
-    let mut saw_end_of_options = false;
+    /// Sets the maximum number of arguments
+    pub fn max_arguments(mut self, max: usize) -> Self {
+        self.max_arguments = Some(max);
+        self
+    }
 
-    for arg in args {
-        if saw_end_of_options {
-            ignored.push(arg);
-            continue;
-        }
+    /// Sets the maximum length of a single argument, in bytes
+    pub fn max_argument_length(mut self, max: usize) -> Self {
+        self.max_argument_length = Some(max);
+        self
+    }
 
-        if arg == "--" {
-            saw_end_of_options = true;
-            continue;
-        }
+    /// Sets the maximum combined length of all arguments, in bytes
+    pub fn max_total_bytes(mut self, max: usize) -> Self {
+        self.max_total_bytes = Some(max);
+        self
+    }
 
-        if let Some(value) = arg.strip_prefix("--") {
-            if let Some((name, value)) = value.split_once('=') {
-                if name.len() < 2 {
-                    return Err(ParseError::MalformedOption(arg));
-                }
-                parsed.push(Arg::SwitchWithValue(SwitchWithValue {
-                    name: name.to_string(),
-                    value: value.to_string(),
-                }));
-            } else {
-                if value.len() < 2 {
-                    return Err(ParseError::MalformedFlag(arg));
-                }
+    /// Sets whether operands containing wildcards (`*`, `?` or `[`) are replaced by the paths they
+    /// match
+    ///
+    /// Shells on Unix expand wildcards before the program runs, but `cmd.exe` and PowerShell do
+    /// not.
+    /// Paths are sorted, and paths that are not valid Unicode are left out.
+    /// A pattern that matches nothing, or is not a valid pattern, is kept as a single operand.
+    ///
+    /// Some operands are never expanded:
+    ///
+    /// - Arguments after the end-of-options marker.
+    /// - An operand right after a flag, because it may turn out to be the value of an option
+    ///   (e.g. `--include *.rs`). Expanding it would hand the first path to
+    ///   [`remove_option`](ArgumentBag::remove_option) and leave the others as stray operands.
+    ///   This also applies to a real flag followed by a pattern (e.g. `-v *.rs`), so put
+    ///   patterns before flags when both are used.
+    ///
+    /// Paths that start with `-` (e.g. a file named `-rf`) are prefixed with `./`, so they are
+    /// never mistaken for switches.
+    ///
+    /// Limits apply to the arguments as given, before expansion.
+    ///
+    /// ```
+    /// use bind_args::ParseOptions;
+    ///
+    /// let mut bag = ParseOptions::new()
+    ///     .expand_globs(true)
+    ///     .parse(["program", "Cargo.t?ml", "*.does-not-exist"])
+    ///     .unwrap();
+    /// assert_eq!(bag.remove_operand().as_deref(), Some("Cargo.toml"));
+    /// assert_eq!(bag.remove_operand().as_deref(), Some("*.does-not-exist"));
+    /// ```
+    #[cfg(feature = "glob")]
+    pub fn expand_globs(mut self, expand: bool) -> Self {
+        self.expand_globs = expand;
+        self
+    }
 
-                parsed.push(Arg::Switch(Switch {
-                    name: value.to_string(),
-                }));
-            }
-            continue;
+    /// Sets whether flag and option names are compared ignoring ASCII case
+    ///
+    /// Names are stored in lowercase, so leftover arguments are returned that way by
+    /// [`ArgumentBag::remove_remaining`].
+    ///
+    /// ```
+    /// use bind_args::ParseOptions;
+    ///
+    /// let mut bag = ParseOptions::new()
+    ///     .ignore_ascii_case(true)
+    ///     .parse(["program", "--Verbose", "--COLOR=never"])
+    ///     .unwrap();
+    /// assert!(bag.remove_flag("verbose"));
+    /// assert_eq!(bag.remove_remaining(), vec![String::from("--color=never")]);
+    /// ```
+    pub fn ignore_ascii_case(mut self, ignore: bool) -> Self {
+        self.folding.ascii_case = ignore;
+        self
+    }
+
+    /// Sets whether flag and option names are compared after Unicode normalization (NFC)
+    ///
+    /// This makes a name typed with decomposed characters (e.g. `o` followed by a combining
+    /// diaeresis) match the precomposed spelling (`ö`).
+    /// Names are stored normalized, so leftover arguments are returned that way by
+    /// [`ArgumentBag::remove_remaining`].
+    ///
+    /// A long name that normalizes to a single character (e.g. the Kelvin sign, which becomes `K`)
+    /// is rejected as malformed rather than treated as a short name.
+    ///
+    /// ```
+    /// use bind_args::ParseOptions;
+    ///
+    /// let mut bag = ParseOptions::new()
+    ///     .normalize_unicode(true)
+    ///     .parse(["program", "--gro\u{308}\u{df}e=10"])
+    ///     .unwrap();
+    /// assert_eq!(bag.remove_option("größe").as_deref(), Some("10"));
+    /// ```
+    #[cfg(feature = "unicode-normalization")]
+    pub fn normalize_unicode(mut self, normalize: bool) -> Self {
+        self.folding.nfc = normalize;
+        self
+    }
+
+    /// Parses the given command line arguments into a [bag](crate::ArgumentBag)
+    ///
+    /// See [`parse`].
+    pub fn parse<I, T>(&self, arguments: I) -> Result<ArgumentBag, ParseError>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("parse").entered();
+
+        let result = self.parse_strings(arguments.into_iter().map(Into::into));
+
+        #[cfg(feature = "tracing")]
+        if let Err(error) = &result {
+            tracing::debug!(error = error.kind(), "rejected command line");
         }
 
-        if let Some(value) = arg.strip_prefix("-") {
-            if let Some((name, value)) = value.split_once('=') {
-                if name.len() != 1 {
-                    return Err(ParseError::MalformedOption(arg));
-                }
+        result
+    }
 
-                parsed.push(Arg::SwitchWithValue(SwitchWithValue {
-                    name: name.to_string(),
-                    value: value.to_string(),
-                }));
-            } else {
-                if value.len() != 1 {
-                    return Err(ParseError::MalformedFlag(arg));
-                }
+    fn parse_strings(
+        &self,
+        arguments: impl Iterator<Item = String>,
+    ) -> Result<ArgumentBag, ParseError> {
+        let (size_hint, _) = arguments.size_hint();
+        let mut usage = Usage::default();
 
-                parsed.push(Arg::Switch(Switch {
-                    name: value.to_string(),
-                }));
-            }
+        // Empty arguments are dropped, but still count towards the limits, so that an endless run
+        // of them is not read forever
+        let mut args = arguments
+            .enumerate()
+            .map(|(index, arg)| self.check(&mut usage, &arg).map(|()| (index, arg)))
+            .filter(|item| !item.as_ref().is_ok_and(|(_, arg)| arg.is_empty()));
 
-            continue;
+        let (_, program_name) = match args.next() {
+            Some(item) => item?,
+            None => return Err(ParseError::MissingProgramName),
+        };
+
+        // Size hints come from the caller, so only trust them up to a point
+        let capacity = size_hint
+            .saturating_sub(1)
+            .min(self.max_arguments.unwrap_or(usize::MAX))
+            .min(MAX_PREALLOCATED_ARGUMENTS);
+
+        let mut builder = BagBuilder::new(program_name, capacity);
+        builder.bag.folding = self.folding;
+        #[cfg(feature = "glob")]
+        {
+            builder.expand_globs = self.expand_globs;
+        }
+        for item in args {
+            let (index, arg) = item?;
+            builder.push(index, arg)?;
         }
+        Ok(builder.finish())
+    }
+
+    // Accounts for `arg` in `usage`, failing if that exceeds a limit
+    fn check(&self, usage: &mut Usage, arg: &str) -> Result<(), ParseError> {
+        usage.arguments += 1;
+        usage.bytes = usage.bytes.saturating_add(arg.len());
 
-        parsed.push(Arg::Operand(Operand {
-            position: operand_count,
-            value: arg,
-        }));
-        operand_count += 1;
+        let exceeded = |max: Option<usize>, value: usize| max.filter(|&max| value > max);
+
+        if let Some(max) = exceeded(self.max_arguments, usage.arguments) {
+            return Err(ParseError::LimitExceeded(Limit::Arguments(max)));
+        }
+        if let Some(max) = exceeded(self.max_argument_length, arg.len()) {
+            return Err(ParseError::LimitExceeded(Limit::ArgumentLength(max)));
+        }
+        if let Some(max) = exceeded(self.max_total_bytes, usage.bytes) {
+            return Err(ParseError::LimitExceeded(Limit::TotalBytes(max)));
+        }
+        Ok(())
     }
+}
 
-    Ok(ArgumentBag {
-        program_name,
-        args: parsed,
-        ignored,
-    })
+// The most argument slots to allocate up front based on a size hint
+const MAX_PREALLOCATED_ARGUMENTS: usize = 1 << 16;
+
+// How much input has been read so far
+#[derive(Default)]
+struct Usage {
+    arguments: usize,
+    bytes: usize,
 }
 
-/// A command line parsing error
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum ParseError {
-    /// Encountered an option without a value  (e.g. `--invalid`)
-    OptionMissingValue(String),
-    /// Encountered an option withuot a name (e.g. `--=value`)
-    MalformedOption(String),
-    /// Encountered a flag without a name (e.g. `-`)
-    MalformedFlag(String),
+/// A limit set on [`ParseOptions`], along with its configured maximum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    /// See [`ParseOptions::max_arguments`]
+    Arguments(usize),
+    /// See [`ParseOptions::max_argument_length`]
+    ArgumentLength(usize),
+    /// See [`ParseOptions::max_total_bytes`]
+    TotalBytes(usize),
 }
 
-impl Display for ParseError {
+impl Display for Limit {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::OptionMissingValue(s) => {
-                write!(f, "Option '{s}' is missing a value")
-            }
-            Self::MalformedOption(s) => {
-                write!(f, "'{s}' is not a valid option")
-            }
-            Self::MalformedFlag(s) => {
-                write!(f, "'{s}' is not a valid flag")
-            }
+            Self::Arguments(max) => write!(f, "more than {max} arguments"),
+            Self::ArgumentLength(max) => write!(f, "an argument longer than {max} bytes"),
+            Self::TotalBytes(max) => write!(f, "more than {max} bytes of arguments"),
         }
     }
 }
 
-impl Error for ParseError {}
+/// The most arguments (including the program name) [`parse_relaxed`] keeps
+pub const RELAXED_MAX_ARGUMENTS: usize = 65_536;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// The most bytes of argument text (including the program name) [`parse_relaxed`] keeps
+pub const RELAXED_MAX_BYTES: usize = 4 * 1024 * 1024;
 
-    #[test]
-    fn end_of_options() {
-        let mut bag = parse(["program", "--", "a", "--b"]).unwrap();
+/// Parses command line arguments on a best-effort basis, for fuzzing and hostile input
+///
+/// Unlike [`parse`], this never fails: arguments that can't be parsed are skipped and described
+/// by a [`RelaxedDiagnostic`] instead.
+///
+/// Parsing never panics and never recurses.
+/// The output is bounded: input is only read up to [`RELAXED_MAX_ARGUMENTS`] arguments (empty
+/// ones included) and [`RELAXED_MAX_BYTES`] bytes, and there is at most one diagnostic per
+/// argument read.
+///
+/// When the input is empty, the bag's program name is empty.
+///
+/// # Example
+///
+/// ```
+/// use bind_args::{parse_relaxed, ParseError, RelaxedDiagnostic};
+///
+/// let (mut bag, diagnostics) = parse_relaxed(["program", "-bad", "--verbose"]);
+/// assert!(bag.remove_flag("verbose"));
+/// assert_eq!(
+///     diagnostics,
+///     vec![RelaxedDiagnostic::Skipped {
+///         index: 1,
+///         error: ParseError::MalformedFlag(String::from("-bad")),
+///     }]
+/// );
+/// ```
+pub fn parse_relaxed<I, T>(arguments: I) -> (ArgumentBag, Vec<RelaxedDiagnostic>)
+where
+    I: IntoIterator<Item = T>,
+    T: Into<String>,
+{
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("parse_relaxed").entered();
+
+    let mut diagnostics = Vec::new();
+    let mut args = arguments.into_iter().map(Into::into).enumerate();
+
+    // Empty arguments are dropped, but still count towards the limits, so that an endless run of
+    // them is not read forever
+    let mut bytes = 0;
+    let mut within_limits = |index: usize, arg: &str| {
+        bytes += arg.len();
+        index < RELAXED_MAX_ARGUMENTS && bytes <= RELAXED_MAX_BYTES
+    };
+
+    let program_name = loop {
+        match args.next() {
+            Some((index, name)) if !within_limits(index, &name) => {
+                diagnostics.push(RelaxedDiagnostic::Truncated { index });
+                return (ArgumentBag::default(), diagnostics);
+            }
+            Some((_, name)) if name.is_empty() => continue,
+            Some((_, name)) => break name,
+            None => {
+                diagnostics.push(RelaxedDiagnostic::Skipped {
+                    index: 0,
+                    error: ParseError::MissingProgramName,
+                });
+                break String::new();
+            }
+        }
+    };
+
+    let mut builder = BagBuilder::new(program_name, 0);
+    for (index, arg) in args {
+        if !within_limits(index, &arg) {
+            diagnostics.push(RelaxedDiagnostic::Truncated { index });
+            break;
+        }
+        if arg.is_empty() {
+            continue;
+        }
+
+        if let Err(error) = builder.push(index, arg) {
+            diagnostics.push(RelaxedDiagnostic::Skipped { index, error });
+        }
+    }
+
+    (builder.finish(), diagnostics)
+}
+
+/// Describes what [`parse_relaxed`] skipped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelaxedDiagnostic {
+    /// The argument at `index` in the input could not be parsed and was skipped
+    Skipped {
+        /// Position of the argument in the input, where `0` is the program name
+        index: usize,
+        /// Why the argument could not be parsed
+        error: ParseError,
+    },
+    /// The input exceeded [`RELAXED_MAX_ARGUMENTS`] or [`RELAXED_MAX_BYTES`], so the argument at
+    /// `index` and everything after it was not read
+    Truncated {
+        /// Position of the first argument that was not read, where `0` is the program name
+        index: usize,
+    },
+}
+
+impl Display for RelaxedDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Skipped { index, error } => {
+                write!(f, "Skipped argument {index}: {error}")
+            }
+            Self::Truncated { index } => {
+                write!(f, "Input too large, stopped reading at argument {index}")
+            }
+        }
+    }
+}
+
+/// Parses command line arguments lazily, one at a time
+///
+/// This is an alternative to [`parse`] for tools that receive enormous argument lists and only
+/// need a single forward pass: nothing is collected into an [`ArgumentBag`].
+///
+/// The first argument is consumed immediately as the program name.
+/// The returned iterator then yields each remaining argument as a [`ParsedArg`], or a
+/// [`ParseError`] for malformed arguments (parsing continues after an error).
+///
+/// A switch followed by a separate value (e.g. `--level 3`) is yielded as a flag followed by an
+/// operand, since there is no way to tell it apart from a flag followed by an operand without
+/// knowing which options the program accepts.
+///
+/// # Example
+///
+/// ```
+/// use bind_args::{parse_streaming, ParsedArg};
+///
+/// let stream = parse_streaming(["program", "-v", "--level=3", "file", "--", "-x"]).unwrap();
+/// assert_eq!(stream.program_name(), "program");
+///
+/// let parsed: Result<Vec<_>, _> = stream.collect();
+/// assert_eq!(
+///     parsed.unwrap(),
+///     vec![
+///         ParsedArg::Flag(String::from("v")),
+///         ParsedArg::Option {
+///             name: String::from("level"),
+///             value: String::from("3")
+///         },
+///         ParsedArg::Operand(String::from("file")),
+///         ParsedArg::Ignored(String::from("-x")),
+///     ]
+/// );
+/// ```
+///
+/// # Errors
+///
+/// Returns [`ParseError::MissingProgramName`] when the input is empty.
+pub fn parse_streaming<I, T>(arguments: I) -> Result<ArgumentStream<I::IntoIter>, ParseError>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<String>,
+{
+    let mut arguments = arguments.into_iter();
+
+    let program_name = loop {
+        match arguments.next().map(Into::into) {
+            Some(name) if name.is_empty() => continue,
+            Some(name) => break name,
+            None => return Err(ParseError::MissingProgramName),
+        }
+    };
+
+    Ok(ArgumentStream {
+        program_name,
+        arguments,
+        classifier: Classifier::default(),
+    })
+}
+
+/// An iterator over parsed command line arguments, created by [`parse_streaming`]
+#[derive(Debug)]
+pub struct ArgumentStream<I> {
+    program_name: String,
+    arguments: I,
+    classifier: Classifier,
+}
+
+impl<I> ArgumentStream<I> {
+    /// Returns the name of the program being run
+    pub fn program_name(&self) -> &str {
+        &self.program_name
+    }
+}
+
+impl<I, T> Iterator for ArgumentStream<I>
+where
+    I: Iterator<Item = T>,
+    T: Into<String>,
+{
+    type Item = Result<ParsedArg, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let arg: String = self.arguments.next()?.into();
+            if arg.is_empty() {
+                continue;
+            }
+
+            let parsed = match self.classifier.classify(arg) {
+                Err(error) => return Some(Err(error)),
+                Ok(Token::EndOfOptions) => continue,
+                Ok(Token::Ignored(arg)) => ParsedArg::Ignored(arg),
+                Ok(Token::Flag { mut token, dashes }) => {
+                    token.drain(..dashes);
+                    ParsedArg::Flag(token)
+                }
+                Ok(Token::Option {
+                    mut token,
+                    dashes,
+                    equals,
+                }) => {
+                    let value = token.split_off(equals + 1);
+                    token.truncate(equals);
+                    token.drain(..dashes);
+                    ParsedArg::Option { name: token, value }
+                }
+                Ok(Token::Operand(arg)) => ParsedArg::Operand(arg),
+            };
+            return Some(Ok(parsed));
+        }
+    }
+}
+
+/// A single argument yielded by [`ArgumentStream`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ParsedArg {
+    /// A switch without a value (e.g. `--verbose` or `-v`), holding its name
+    Flag(String),
+    /// A switch with an `=`-separated value (e.g. `--level=3` or `-l=3`)
+    Option {
+        /// The name of the option
+        name: String,
+        /// The value of the option
+        value: String,
+    },
+    /// An operand (e.g. `file.txt`)
+    Operand(String),
+    /// An argument that appeared after the end-of-options marker (i.e. `--`), as-is
+    Ignored(String),
+}
+
+// How a single argument should be interpreted
+enum Token {
+    EndOfOptions,
+    Ignored(String),
+    Flag {
+        token: String,
+        // Number of leading dashes
+        dashes: usize,
+    },
+    Option {
+        token: String,
+        // Number of leading dashes
+        dashes: usize,
+        // Index of the `=` separating the name from the value
+        equals: usize,
+    },
+    Operand(String),
+}
+
+// Classifies arguments one at a time, remembering whether the end-of-options marker was seen
+#[derive(Debug, Default)]
+struct Classifier {
+    saw_end_of_options: bool,
+}
+
+impl Classifier {
+    fn classify(&mut self, arg: String) -> Result<Token, ParseError> {
+        if self.saw_end_of_options {
+            trace!(length = arg.len(), "ignored after end-of-options marker");
+            return Ok(Token::Ignored(arg));
+        }
+
+        if arg == "--" {
+            trace!("end-of-options marker");
+            self.saw_end_of_options = true;
+            return Ok(Token::EndOfOptions);
+        }
+
+        let dashes = if arg.starts_with("--") {
+            2
+        } else if arg.starts_with('-') {
+            1
+        } else {
+            0
+        };
+
+        if dashes == 0 {
+            trace!(length = arg.len(), "classified as operand");
+            return Ok(Token::Operand(arg));
+        }
+
+        if let Some(equals) = arg.find('=') {
+            if !is_valid_name(&arg[dashes..equals], dashes) {
+                return Err(ParseError::MalformedOption(arg));
+            }
+
+            trace!(
+                name = &arg[dashes..equals],
+                value_length = arg.len() - equals - 1,
+                "classified as option"
+            );
+            Ok(Token::Option {
+                token: arg,
+                dashes,
+                equals,
+            })
+        } else {
+            if !is_valid_name(&arg[dashes..], dashes) {
+                return Err(ParseError::MalformedFlag(arg));
+            }
+
+            trace!(name = &arg[dashes..], "classified as flag");
+            Ok(Token::Flag { token: arg, dashes })
+        }
+    }
+}
+
+// Parses arguments one at a time into an `ArgumentBag`
+struct BagBuilder {
+    bag: ArgumentBag,
+    operand_count: usize,
+    classifier: Classifier,
+    #[cfg(feature = "glob")]
+    expand_globs: bool,
+}
+
+impl BagBuilder {
+    fn new(program_name: String, capacity: usize) -> Self {
+        Self {
+            bag: ArgumentBag {
+                program_name,
+                args: Vec::with_capacity(capacity),
+                ..ArgumentBag::default()
+            },
+            operand_count: 0,
+            classifier: Classifier::default(),
+            #[cfg(feature = "glob")]
+            expand_globs: false,
+        }
+    }
+
+    // Parses `arg`, found at `index` in the input, into the bag, leaving the bag unchanged if it is
+    // malformed
+    fn push(&mut self, index: usize, arg: String) -> Result<(), ParseError> {
+        let arg = match self.classifier.classify(arg)? {
+            Token::EndOfOptions => return Ok(()),
+            Token::Ignored(arg) => {
+                self.bag.ignored.push(arg);
+                return Ok(());
+            }
+            Token::Flag { token, dashes } => {
+                let Some(spelling) = self.intern(&token, dashes) else {
+                    return Err(ParseError::MalformedFlag(token));
+                };
+                Arg::Switch(Switch { spelling, index })
+            }
+            Token::Option {
+                mut token,
+                dashes,
+                equals,
+            } => {
+                let Some(spelling) = self.intern(&token[..equals], dashes) else {
+                    return Err(ParseError::MalformedOption(token));
+                };
+
+                // The value is kept in the token's own buffer
+                token.drain(..=equals);
+                Arg::SwitchWithValue(SwitchWithValue {
+                    spelling,
+                    value: token.into_boxed_str(),
+                    index,
+                })
+            }
+            Token::Operand(token) => {
+                // An operand right after a flag may be that flag's value, so it is kept as-is
+                #[cfg(feature = "glob")]
+                if self.expand_globs && !matches!(self.bag.args.last(), Some(Arg::Switch(_))) {
+                    for path in expand_glob(token) {
+                        let arg = self.operand(path);
+                        self.bag.args.push(arg);
+                    }
+                    return Ok(());
+                }
+
+                self.operand(token)
+            }
+        };
+
+        self.bag.args.push(arg);
+        Ok(())
+    }
+
+    fn operand(&mut self, token: String) -> Arg {
+        self.operand_count += 1;
+        Arg::Operand(Operand {
+            position: self.operand_count - 1,
+            token: token.into_boxed_str(),
+        })
+    }
+
+    // Returns the shared copy of `spelling`, adding it to the bag if this is its first use.
+    //
+    // Returns `None` when folding leaves a name that is no longer valid, e.g. when NFC turns the
+    // Kelvin sign in `--\u{212A}` into `K`.
+    fn intern(&mut self, spelling: &str, dashes: usize) -> Option<Arc<str>> {
+        let spelling = self.bag.folding.apply(spelling);
+        if !is_valid_name(&spelling[dashes..], dashes) {
+            return None;
+        }
+
+        let next_index = self.bag.args.len();
+        if let Some(occurrences) = self.bag.switches.get_mut(&*spelling) {
+            occurrences.slots.end = next_index + 1;
+            return Some(Arc::clone(&occurrences.spelling));
+        }
+
+        let interned: Arc<str> = Arc::from(spelling);
+        let occurrences = Occurrences {
+            spelling: Arc::clone(&interned),
+            slots: next_index..next_index + 1,
+        };
+        self.bag.switches.insert(Arc::clone(&interned), occurrences);
+        Some(interned)
+    }
+
+    fn finish(mut self) -> ArgumentBag {
+        self.bag.live = self.bag.args.len();
+        self.bag
+    }
+}
+
+// Returns the paths matching `pattern`, or `pattern` itself if there are none
+#[cfg(feature = "glob")]
+fn expand_glob(pattern: String) -> Vec<String> {
+    if !pattern.contains(['*', '?', '[']) {
+        return vec![pattern];
+    }
+    let Ok(paths) = glob::glob(&pattern) else {
+        trace!("operand is not a valid pattern, keeping it as-is");
+        return vec![pattern];
+    };
+
+    let mut matches: Vec<String> = paths
+        .filter_map(Result::ok)
+        .filter_map(path_operand)
+        .collect();
+    trace!(matches = matches.len(), "expanded operand");
+
+    if matches.is_empty() {
+        matches.push(pattern);
+    }
+    matches
+}
+
+// Returns `path` as an operand, unless it is not valid Unicode
+#[cfg(feature = "glob")]
+fn path_operand(path: std::path::PathBuf) -> Option<String> {
+    // A leading dash would make the path parse as a switch
+    let path = match path.as_os_str().as_encoded_bytes().first() {
+        Some(b'-') => std::path::Path::new(".").join(path),
+        _ => path,
+    };
+    path.into_os_string().into_string().ok()
+}
+
+/// A command line parsing error
+///
+/// New kinds of errors may be added in minor releases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseError {
+    /// Encountered an option without a value  (e.g. `--invalid`)
+    OptionMissingValue(String),
+    /// Encountered an option withuot a name (e.g. `--=value`)
+    MalformedOption(String),
+    /// Encountered a flag without a name (e.g. `-`)
+    MalformedFlag(String),
+    /// The input did not start with the name of the program
+    MissingProgramName,
+    /// The input exceeded a limit set on [`ParseOptions`]
+    LimitExceeded(Limit),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OptionMissingValue(s) => {
+                write!(f, "Option '{s}' is missing a value")
+            }
+            Self::MalformedOption(s) => {
+                write!(f, "'{s}' is not a valid option")
+            }
+            Self::MalformedFlag(s) => {
+                write!(f, "'{s}' is not a valid flag")
+            }
+            Self::MissingProgramName => {
+                write!(f, "Missing program name")
+            }
+            Self::LimitExceeded(limit) => {
+                write!(f, "Command line has {limit}")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+#[cfg(feature = "tracing")]
+impl ParseError {
+    // Describes the error without the argument that caused it, which may be sensitive
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::OptionMissingValue(_) => "option missing value",
+            Self::MalformedOption(_) => "malformed option",
+            Self::MalformedFlag(_) => "malformed flag",
+            Self::MissingProgramName => "missing program name",
+            Self::LimitExceeded(_) => "limit exceeded",
+        }
+    }
+}
+
+#[cfg(feature = "miette")]
+impl ParseError {
+    // The command line argument that caused the error
+    fn argument(&self) -> Option<&String> {
+        match self {
+            Self::OptionMissingValue(s) | Self::MalformedOption(s) | Self::MalformedFlag(s) => {
+                Some(s)
+            }
+            Self::MissingProgramName | Self::LimitExceeded(_) => None,
+        }
+    }
+
+    // What the label on the argument that caused the error says
+    fn label(&self) -> Option<&'static str> {
+        match self {
+            Self::OptionMissingValue(_) => Some("this option"),
+            Self::MalformedOption(_) => Some("invalid option"),
+            Self::MalformedFlag(_) => Some("invalid flag"),
+            Self::MissingProgramName | Self::LimitExceeded(_) => None,
+        }
+    }
+
+    /// Attaches the command line that failed to parse, so the error is rendered in context.
+    ///
+    /// `arguments` should be the same arguments that were parsed.
+    /// They are joined with spaces, without any quoting.
+    ///
+    /// Requires the `miette` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let arguments = ["program", "--verbose", "-long"];
+    /// let error = bind_args::parse(arguments).unwrap_err().with_command_line(arguments);
+    /// assert_eq!(error.command_line(), "program --verbose -long");
+    /// ```
+    pub fn with_command_line<I, T>(self, arguments: I) -> CommandLineError
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let mut line = String::new();
+        let mut span = None;
+
+        // Parsing stops at the first invalid argument, so it is the first one equal to the one
+        // in the error
+        let arguments = arguments.into_iter().filter(|s| !s.as_ref().is_empty());
+        for (i, argument) in arguments.enumerate() {
+            let argument = argument.as_ref();
+            if i > 0 {
+                line.push(' ');
+            }
+            if i > 0 && span.is_none() && self.argument().is_some_and(|s| s == argument) {
+                span = Some(line.len()..line.len() + argument.len());
+            }
+            line.push_str(argument);
+        }
+
+        CommandLineError {
+            error: self,
+            line,
+            span,
+        }
+    }
+}
+
+/// Renders the offending argument on its own, with a label pointing at it.
+///
+/// Use [`ParseError::with_command_line`] to render it within the whole command line instead.
+///
+/// Requires the `miette` feature.
+#[cfg(feature = "miette")]
+impl miette::Diagnostic for ParseError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        let code = match self {
+            Self::OptionMissingValue(_) => "bind_args::option_missing_value",
+            Self::MalformedOption(_) => "bind_args::malformed_option",
+            Self::MalformedFlag(_) => "bind_args::malformed_flag",
+            Self::MissingProgramName => "bind_args::missing_program_name",
+            Self::LimitExceeded(_) => "bind_args::limit_exceeded",
+        };
+        Some(Box::new(code))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        let help = match self {
+            Self::OptionMissingValue(_) => "supply a value with `--option=value` or `--option value`",
+            Self::MalformedOption(_) | Self::MalformedFlag(_) => {
+                "long names need at least two characters (`--name`) and short names exactly one (`-n`)"
+            }
+            Self::MissingProgramName => "the first argument must be the name of the program",
+            Self::LimitExceeded(_) => "shorten the command line",
+        };
+        Some(Box::new(help))
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        self.argument().map(|s| s as &dyn miette::SourceCode)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+        let span = miette::LabeledSpan::at(0..self.argument()?.len(), self.label()?);
+        Some(Box::new(std::iter::once(span)))
+    }
+}
+
+/// A [`ParseError`] along with the command line it came from
+///
+/// Created by [`ParseError::with_command_line`].
+/// Its `miette::Diagnostic` implementation renders the whole command line, with a label pointing
+/// at the argument that caused the error.
+///
+/// Requires the `miette` feature.
+#[cfg(feature = "miette")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandLineError {
+    error: ParseError,
+    line: String,
+    span: Option<Range<usize>>,
+}
+
+#[cfg(feature = "miette")]
+impl CommandLineError {
+    /// Returns the error that occurred
+    pub fn error(&self) -> &ParseError {
+        &self.error
+    }
+
+    /// Returns the command line, as rendered for display
+    pub fn command_line(&self) -> &str {
+        &self.line
+    }
+}
+
+#[cfg(feature = "miette")]
+impl Display for CommandLineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+#[cfg(feature = "miette")]
+impl Error for CommandLineError {}
+
+#[cfg(feature = "miette")]
+impl miette::Diagnostic for CommandLineError {
+    fn code<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.error.code()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        self.error.help()
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        Some(&self.line)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+        let span = miette::LabeledSpan::at(self.span.clone()?, self.error.label()?);
+        Some(Box::new(std::iter::once(span)))
+    }
+}
+
+/// An error that ends a command line program
+///
+/// It wraps either a [`ParseError`] or an application error, along with the exit code the process
+/// should terminate with.
+///
+/// # Exit codes
+///
+/// Returning `Result<(), CliError>` from `main` always exits with code `1`, because the standard
+/// library handles the `Result` and ignores the exit code.
+/// (The `Debug` output is the same as the `Display` output, so it at least prints
+/// `Error: <message>` instead of a struct dump.)
+///
+/// To exit with the error's code, return an `ExitCode` from `main` built by
+/// [`report`](CliError::report), or call [`exit`](CliError::exit).
+///
+/// # Example
+///
+/// ```no_run
+/// use bind_args::{parse_env, CliError};
+/// use std::process::ExitCode;
+///
+/// fn run() -> Result<(), CliError> {
+///     let mut bag = parse_env()?;
+///     if !bag.is_empty() {
+///         let unexpected = bag.remove_remaining().join(", ");
+///         return Err(CliError::usage(format!("unexpected argument(s): {unexpected}")));
+///     }
+///     Ok(())
+/// }
+///
+/// fn main() -> ExitCode {
+///     match run() {
+///         Ok(()) => ExitCode::SUCCESS,
+///         Err(error) => error.report(),
+///     }
+/// }
+/// ```
+pub struct CliError {
+    error: Box<dyn Error + Send + Sync>,
+    exit_code: u8,
+}
+
+impl CliError {
+    /// The exit code used for command line usage errors (`EX_USAGE` from `sysexits.h`)
+    pub const USAGE: u8 = 64;
+
+    /// Wraps an application error, exiting with code `1`
+    pub fn new(error: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self {
+            error: error.into(),
+            exit_code: 1,
+        }
+    }
+
+    /// Wraps an error caused by invalid command line usage, exiting with [`CliError::USAGE`]
+    pub fn usage(error: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self::new(error).with_exit_code(Self::USAGE)
+    }
+
+    /// Replaces the exit code the process should terminate with
+    pub fn with_exit_code(mut self, code: u8) -> Self {
+        self.exit_code = code;
+        self
+    }
+
+    /// Returns the exit code the process should terminate with
+    pub fn exit_code(&self) -> u8 {
+        self.exit_code
+    }
+
+    /// Prints the error to standard error and returns the exit code to terminate with
+    pub fn report(self) -> std::process::ExitCode {
+        eprintln!("{self}");
+        std::process::ExitCode::from(self.exit_code)
+    }
+
+    /// Prints the error to standard error and exits the process with its exit code
+    ///
+    /// Like [`std::process::exit`], this does not run destructors.
+    pub fn exit(self) -> ! {
+        eprintln!("{self}");
+        std::process::exit(self.exit_code.into())
+    }
+}
+
+impl From<ParseError> for CliError {
+    fn from(error: ParseError) -> Self {
+        Self::usage(error)
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::fmt::Debug for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Error for CliError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        // `Display` already shows the wrapped error, so skip straight to its cause
+        self.error.source()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn end_of_options() {
+        let mut bag = parse(["program", "--", "a", "--b"]).unwrap();
         let ignored = bag.remove_ignored();
 
         assert_eq!(ignored, vec![String::from("a"), String::from("--b")]);
@@ -471,6 +1774,55 @@ mod tests {
         assert_eq!(result, Err(ParseError::MalformedFlag("-long".to_string())));
     }
 
+    #[cfg(feature = "miette")]
+    #[test]
+    fn miette_diagnostic() {
+        use miette::Diagnostic;
+
+        let error = parse(["program", "-long"]).unwrap_err();
+        let labels: Vec<_> = error.labels().unwrap().collect();
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels[0].offset(), 0);
+        assert_eq!(labels[0].len(), "-long".len());
+        assert_eq!(
+            error.code().unwrap().to_string(),
+            "bind_args::malformed_flag"
+        );
+
+        let arguments = ["program", "", "-", "-v", "-"];
+        let error = parse(arguments).unwrap_err().with_command_line(arguments);
+        assert_eq!(error.command_line(), "program - -v -");
+        let labels: Vec<_> = error.labels().unwrap().collect();
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels[0].offset(), "program ".len());
+        assert_eq!(labels[0].len(), 1);
+        assert_eq!(
+            error.code().unwrap().to_string(),
+            "bind_args::malformed_flag"
+        );
+
+        let arguments = ["program", "a", "b"];
+        let error = ParseOptions::new()
+            .max_arguments(2)
+            .parse(arguments)
+            .unwrap_err()
+            .with_command_line(arguments);
+        assert!(error.labels().is_none());
+        assert!(error.source_code().is_some());
+    }
+
+    #[test]
+    fn cli_error() {
+        let error = CliError::from(ParseError::MalformedFlag("-".to_string()));
+        assert_eq!(error.exit_code(), CliError::USAGE);
+        assert_eq!(format!("{error:?}"), "'-' is not a valid flag");
+
+        let error = CliError::new("something broke").with_exit_code(3);
+        assert_eq!(error.exit_code(), 3);
+        assert_eq!(format!("{error:?}"), format!("{error}"));
+        assert_eq!(error.report(), std::process::ExitCode::from(3));
+    }
+
     #[test]
     fn remove_option() {
         // =-separated
@@ -515,6 +1867,484 @@ mod tests {
         assert!(result.is_empty());
     }
 
+    #[test]
+    fn missing_program_name() {
+        assert_eq!(
+            parse(Vec::<String>::new()),
+            Err(ParseError::MissingProgramName)
+        );
+        assert_eq!(parse(["", ""]), Err(ParseError::MissingProgramName));
+    }
+
+    // Every command line made of up to three tokens built from these fragments
+    fn generated_command_lines() -> Vec<Vec<String>> {
+        const FRAGMENTS: [&str; 7] = ["", "-", "--", "=", "a", "é", "a=b"];
+
+        let mut tokens: Vec<String> = FRAGMENTS
+            .iter()
+            .flat_map(|x| FRAGMENTS.iter().map(move |y| format!("{x}{y}")))
+            .collect();
+        tokens.sort();
+        tokens.dedup();
+
+        let mut lines = vec![vec![]];
+        let mut start = 0;
+        for _ in 0..3 {
+            let end = lines.len();
+            let next: Vec<Vec<String>> = lines[start..]
+                .iter()
+                .flat_map(|line| {
+                    tokens.iter().map(move |token| {
+                        let mut line = line.clone();
+                        line.push(token.clone());
+                        line
+                    })
+                })
+                .collect();
+            lines.extend(next);
+            start = end;
+        }
+        lines
+    }
+
+    #[test]
+    fn invariants() {
+        for line in generated_command_lines() {
+            let mut argv = vec![String::from("program")];
+            argv.extend(line.iter().cloned());
+
+            // Relaxed parsing agrees with strict parsing, apart from skipping malformed arguments
+            let (relaxed, diagnostics) = parse_relaxed(argv.clone());
+
+            // Parsing never panics and either succeeds or reports a malformed argument
+            let mut bag = match parse(argv.clone()) {
+                Ok(bag) => bag,
+                Err(ParseError::MalformedFlag(arg)) | Err(ParseError::MalformedOption(arg)) => {
+                    assert!(line.contains(&arg), "{argv:?}: unknown argument {arg:?}");
+                    assert!(!diagnostics.is_empty(), "{argv:?}");
+                    continue;
+                }
+                Err(e) => panic!("{argv:?}: unexpected error {e:?}"),
+            };
+            assert_eq!(relaxed, bag, "{argv:?}");
+            assert!(diagnostics.is_empty(), "{argv:?}");
+
+            // Leftover arguments parse back into the same arguments
+            let remaining = bag.remove_remaining();
+            let mut reparsed =
+                parse(std::iter::once("program".to_string()).chain(remaining.clone()))
+                    .unwrap_or_else(|e| panic!("{argv:?}: {remaining:?} does not round-trip: {e}"));
+            assert_eq!(reparsed.remove_remaining(), remaining, "{argv:?}");
+
+            // A drained bag stays drained
+            assert!(bag.is_empty(), "{argv:?}");
+            assert!(bag.remove_remaining().is_empty(), "{argv:?}");
+            assert_eq!(bag.remove_operand(), None, "{argv:?}");
+            for name in ["a", "aa", "é", "-a"] {
+                assert!(!bag.remove_flag(name), "{argv:?}");
+                assert_eq!(bag.remove_option(name), None, "{argv:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn interleaved_removals() {
+        let mut bag = parse(["program", "a", "--opt", "b", "c", "--flag", "d"]).unwrap();
+        assert_eq!(bag.remove_operand().as_deref(), Some("a"));
+        assert_eq!(bag.remove_option("opt").as_deref(), Some("b"));
+        assert!(bag.remove_flag("flag"));
+        assert_eq!(bag.remove_operand().as_deref(), Some("c"));
+        assert!(!bag.is_empty());
+        assert_eq!(bag.remove_remaining(), vec![String::from("d")]);
+        assert!(bag.is_empty());
+        assert_eq!(bag.remove_operand(), None);
+    }
+
+    #[test]
+    fn repeated_names() {
+        let mut bag = parse(["program", "-D=a", "-D", "-D=b", "-D", "c", "-v", "--xy"]).unwrap();
+        assert!(bag.remove_flag("D"));
+        assert_eq!(bag.remove_option("D").as_deref(), Some("a"));
+        assert_eq!(bag.remove_option("D").as_deref(), Some("b"));
+        assert_eq!(bag.remove_option("D").as_deref(), Some("c"));
+        assert_eq!(bag.remove_option("D"), None);
+        assert!(!bag.remove_flag("D"));
+        assert!(!bag.remove_flag("unknown"));
+        assert_eq!(
+            bag.remove_remaining(),
+            vec![String::from("-v"), String::from("--xy")]
+        );
+    }
+
+    #[test]
+    fn dereferenced() {
+        let path = std::env::temp_dir().join(format!("bind-args-{}.txt", std::process::id()));
+        std::fs::write(&path, "from a file\n").unwrap();
+
+        let mut bag = parse([
+            String::from("program"),
+            format!("--key=@{}", path.display()),
+            String::from("--missing=@"),
+        ])
+        .unwrap();
+        assert_eq!(
+            bag.remove_option_dereferenced("key").unwrap().as_deref(),
+            Some("from a file\n")
+        );
+        let error = bag.remove_option_dereferenced("missing").unwrap_err();
+        assert!(error.to_string().starts_with("--missing: : "));
+        assert_eq!(bag.remove_option_dereferenced("missing").unwrap(), None);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn stdin_value() {
+        assert_eq!(
+            read_option_value("data", &b"{}\n"[..], false).unwrap(),
+            "{}\n"
+        );
+
+        let error = read_option_value("d", &b""[..], true).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(error.to_string().starts_with("-d: "));
+
+        let error = read_option_value("data", &b"\xff"[..], false).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("--data: stdin: "));
+    }
+
+    #[cfg(feature = "glob")]
+    #[test]
+    fn expand_globs() {
+        let dir = std::env::temp_dir().join(format!("bind-args-glob-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in ["b.log", "a.log", "c.txt"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        let dir = dir.to_str().unwrap();
+
+        let arguments = [
+            String::from("program"),
+            format!("{dir}/*.log"),
+            format!("--path={dir}/*.txt"),
+            format!("{dir}/*.none"),
+            String::from("--include"),
+            format!("{dir}/*.log"),
+            String::from("--"),
+            format!("{dir}/*.log"),
+        ];
+        let options = ParseOptions::new().expand_globs(true);
+        let mut bag = options.parse(arguments.clone()).unwrap();
+        assert_eq!(bag.remove_option("include"), Some(format!("{dir}/*.log")));
+        assert_eq!(
+            bag.remove_remaining(),
+            vec![
+                format!("{dir}/a.log"),
+                format!("{dir}/b.log"),
+                format!("--path={dir}/*.txt"),
+                format!("{dir}/*.none"),
+            ]
+        );
+        assert_eq!(bag.remove_ignored(), vec![format!("{dir}/*.log")]);
+
+        // A path that looks like a switch stays an operand
+        let path = std::path::PathBuf::from("-rf");
+        let operand = path_operand(path).unwrap();
+        assert!(!operand.starts_with('-') && operand.ends_with("-rf"));
+        let mut bag = parse(["program", &operand]).unwrap();
+        assert_eq!(bag.remove_operand(), Some(operand));
+
+        let mut bag = parse(arguments).unwrap();
+        assert_eq!(bag.remove_operand(), Some(format!("{dir}/*.log")));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn occurrences() {
+        let mut bag = parse(["program", "", "-D", "-D", "a", "--", "x"]).unwrap();
+        assert_eq!(
+            bag.remove_option_occurrences("D"),
+            vec![(3, String::from("a"))]
+        );
+        assert!(bag.remove_flag("D"));
+        assert!(bag.is_empty());
+
+        let (mut bag, _) = parse_relaxed(["program", "-", "--level=1", "--level", "2", "--level"]);
+        bag.set_precedence(Precedence::LastWins);
+        assert_eq!(
+            bag.remove_option_occurrences("level"),
+            vec![(2, String::from("1")), (3, String::from("2"))]
+        );
+        assert!(bag.remove_flag("level"));
+        assert!(bag.remove_option_occurrences("unknown").is_empty());
+    }
+
+    #[test]
+    fn folding() {
+        let arguments = ["program", "--Log-Level=debug", "-V", "-v", "--\u{e9}"];
+        let mut bag = ParseOptions::new()
+            .ignore_ascii_case(true)
+            .parse(arguments)
+            .unwrap();
+        assert_eq!(bag.remove_option("LOG-level").as_deref(), Some("debug"));
+        assert!(bag.remove_flag("v"));
+        assert!(bag.remove_flag("V"));
+        assert!(!bag.remove_flag("\u{c9}"));
+        assert_eq!(bag.remove_remaining(), vec![String::from("--\u{e9}")]);
+
+        let mut bag = parse(arguments).unwrap();
+        assert_eq!(bag.remove_option("log-level"), None);
+        assert!(bag.remove_flag("v"));
+        assert!(!bag.remove_flag("v"));
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn nfc_folding() {
+        let decomposed = "e\u{301}t\u{e9}";
+        let options = ParseOptions::new().normalize_unicode(true);
+
+        let mut bag = options
+            .parse(["program", &format!("--{decomposed}")])
+            .unwrap();
+        assert!(bag.remove_flag(decomposed));
+        let mut bag = options
+            .parse(["program", &format!("--{decomposed}")])
+            .unwrap();
+        assert!(bag.remove_flag("\u{e9}t\u{e9}"));
+        assert!(bag.is_empty());
+
+        let mut bag = options
+            .clone()
+            .ignore_ascii_case(true)
+            .parse(["program", "--E\u{301}T\u{c9}"])
+            .unwrap();
+        assert!(!bag.remove_flag("\u{e9}t\u{e9}"));
+        assert!(bag.remove_flag("\u{c9}t\u{c9}"));
+
+        let mut bag = parse(["program", &format!("--{decomposed}")]).unwrap();
+        assert!(!bag.remove_flag("\u{e9}t\u{e9}"));
+
+        // A long switch never turns into a short one
+        assert_eq!(
+            options.parse(["program", "--\u{212A}", "-K"]),
+            Err(ParseError::MalformedFlag(String::from("--\u{212A}")))
+        );
+        assert_eq!(
+            options.parse(["program", "--\u{212A}=1"]),
+            Err(ParseError::MalformedOption(String::from("--\u{212A}=1")))
+        );
+
+        // Leftovers keep their dashes and parse back the same
+        let argv = [
+            "program",
+            "-K",
+            "--\u{212A}\u{212A}",
+            "--\u{212A}\u{212A}=1",
+            "-K=2",
+        ];
+        let mut bag = options.parse(argv).unwrap();
+        assert!(bag.remove_flag("K"));
+        assert!(!bag.remove_flag("K"));
+        assert_eq!(bag.remove_option("K").as_deref(), Some("2"));
+        let remaining = bag.remove_remaining();
+        assert_eq!(
+            remaining,
+            vec![String::from("--KK"), String::from("--KK=1")]
+        );
+
+        let mut reparsed = options
+            .parse(std::iter::once(String::from("program")).chain(remaining.clone()))
+            .unwrap();
+        assert!(reparsed.remove_flag("\u{212A}\u{212A}"));
+        assert_eq!(reparsed.remove_option("KK").as_deref(), Some("1"));
+        assert!(reparsed.is_empty());
+    }
+
+    #[test]
+    fn equality() {
+        let mut bag = parse(["program", "-D", "-D"]).unwrap();
+        assert!(bag.remove_flag("D"));
+
+        // A lookup that removes nothing leaves the bag equal to its copy
+        let before = bag.clone();
+        assert_eq!(bag.remove_option("D"), None);
+        assert_eq!(bag, before);
+
+        bag.set_precedence(Precedence::LastWins);
+        assert_eq!(bag, before);
+
+        assert!(bag.remove_flag("D"));
+        assert_ne!(bag, before);
+
+        // Where an argument was given does not matter
+        assert_eq!(parse(["program", "", "-v"]), parse(["program", "-v"]));
+        let mut bag = parse(["program", "-x", "-v", "a", "b=c"]).unwrap();
+        assert!(bag.remove_flag("x"));
+        assert_eq!(bag.remove_operand().as_deref(), Some("a"));
+        assert_eq!(bag, parse(["program", "-v", "b=c"]).unwrap());
+        assert_ne!(bag, parse(["program", "-v", "b=d"]).unwrap());
+        assert_ne!(bag, parse(["program", "-v"]).unwrap());
+    }
+
+    #[test]
+    fn last_wins() {
+        let mut bag = parse([
+            "program", "-D=a", "-D", "-D=b", "-D", "c", "--log", "x", "--log",
+        ])
+        .unwrap();
+        bag.set_precedence(Precedence::LastWins);
+        assert_eq!(bag.remove_option("D").as_deref(), Some("c"));
+        assert_eq!(bag.remove_option("D").as_deref(), Some("b"));
+        assert!(bag.remove_flag("D"));
+        assert!(!bag.remove_flag("D"));
+        assert_eq!(bag.remove_option("D").as_deref(), Some("a"));
+        assert_eq!(bag.remove_option("D"), None);
+
+        // The last `--log` has no value, so it is a flag
+        assert_eq!(bag.remove_option("log"), None);
+        assert!(bag.remove_flag("log"));
+        assert_eq!(bag.remove_option("log").as_deref(), Some("x"));
+        assert!(bag.is_empty());
+    }
+
+    #[test]
+    fn relaxed() {
+        let (mut bag, diagnostics) = parse_relaxed(["program", "-", "a", "--=x", "--", "-"]);
+        assert_eq!(
+            diagnostics,
+            vec![
+                RelaxedDiagnostic::Skipped {
+                    index: 1,
+                    error: ParseError::MalformedFlag(String::from("-"))
+                },
+                RelaxedDiagnostic::Skipped {
+                    index: 3,
+                    error: ParseError::MalformedOption(String::from("--=x"))
+                },
+            ]
+        );
+        assert_eq!(bag.remove_remaining(), vec![String::from("a")]);
+        assert_eq!(bag.remove_ignored(), vec![String::from("-")]);
+
+        let (bag, diagnostics) = parse_relaxed(Vec::<String>::new());
+        assert_eq!(bag.program_name, "");
+        assert_eq!(
+            diagnostics,
+            vec![RelaxedDiagnostic::Skipped {
+                index: 0,
+                error: ParseError::MissingProgramName
+            }]
+        );
+    }
+
+    #[test]
+    fn relaxed_limits() {
+        let argv = std::iter::repeat_n("a", RELAXED_MAX_ARGUMENTS + 10);
+        let (mut bag, diagnostics) = parse_relaxed(argv);
+        assert_eq!(
+            diagnostics,
+            vec![RelaxedDiagnostic::Truncated {
+                index: RELAXED_MAX_ARGUMENTS
+            }]
+        );
+        assert_eq!(bag.remove_remaining().len(), RELAXED_MAX_ARGUMENTS - 1);
+
+        let huge = "a".repeat(RELAXED_MAX_BYTES);
+        let (mut bag, diagnostics) = parse_relaxed(["program", "b", &huge, "c"]);
+        assert_eq!(diagnostics, vec![RelaxedDiagnostic::Truncated { index: 2 }]);
+        assert_eq!(bag.remove_remaining(), vec![String::from("b")]);
+
+        // Empty arguments count too, even though they are dropped
+        let truncated = vec![RelaxedDiagnostic::Truncated {
+            index: RELAXED_MAX_ARGUMENTS,
+        }];
+        let endless = std::iter::once("program").chain(std::iter::repeat(""));
+        let (bag, diagnostics) = parse_relaxed(endless);
+        assert_eq!(diagnostics, truncated);
+        assert_eq!(bag.program_name, "program");
+        assert!(bag.is_empty());
+
+        let (bag, diagnostics) = parse_relaxed(std::iter::repeat(""));
+        assert_eq!(diagnostics, truncated);
+        assert_eq!(bag.program_name, "");
+    }
+
+    #[test]
+    fn streaming() {
+        let stream = parse_streaming(["", "program", "", "-", "a", "--x=1", "--"]).unwrap();
+        assert_eq!(stream.program_name(), "program");
+        assert_eq!(
+            stream.collect::<Vec<_>>(),
+            vec![
+                Err(ParseError::MalformedFlag(String::from("-"))),
+                Ok(ParsedArg::Operand(String::from("a"))),
+                Err(ParseError::MalformedOption(String::from("--x=1"))),
+            ]
+        );
+
+        assert_eq!(
+            parse_streaming([""]).unwrap_err(),
+            ParseError::MissingProgramName
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let options = ParseOptions::new().max_total_bytes(10);
+        assert!(options.parse(["program", "abc"]).is_ok());
+        assert_eq!(
+            options.parse(["program", "abcd"]),
+            Err(ParseError::LimitExceeded(Limit::TotalBytes(10)))
+        );
+
+        // Input past the limit is never read
+        let options = ParseOptions::new().max_arguments(3);
+        let endless = std::iter::repeat("a");
+        assert_eq!(
+            options.parse(endless),
+            Err(ParseError::LimitExceeded(Limit::Arguments(3)))
+        );
+
+        // Empty arguments count too, even though they are dropped
+        assert_eq!(
+            options.parse(["program", "", "", "a"]),
+            Err(ParseError::LimitExceeded(Limit::Arguments(3)))
+        );
+        let endless = std::iter::once("program").chain(std::iter::repeat(""));
+        assert_eq!(
+            options.parse(endless),
+            Err(ParseError::LimitExceeded(Limit::Arguments(3)))
+        );
+        assert_eq!(
+            options.parse(std::iter::repeat("")),
+            Err(ParseError::LimitExceeded(Limit::Arguments(3)))
+        );
+    }
+
+    #[test]
+    fn untrusted_size_hint() {
+        // Claims to have far more items than it does
+        struct Boastful(std::vec::IntoIter<&'static str>);
+
+        impl Iterator for Boastful {
+            type Item = &'static str;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.0.next()
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (usize::MAX, None)
+            }
+        }
+
+        let mut bag = parse(Boastful(vec!["program", "a"].into_iter())).unwrap();
+        assert_eq!(bag.remove_operand().as_deref(), Some("a"));
+    }
+
     #[test]
     fn remove_order_matters() {
         let mut bag = parse(["program", "--option", "value"]).unwrap();